@@ -2,14 +2,22 @@ use anyhow::{anyhow, Result};
 use chrono::Local;
 use clap::{Arg, ArgAction, ArgGroup, ArgMatches, Command};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fs;
 use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
 use std::path::{Path, PathBuf};
-use std::process::Command as StdCommand;
+use std::process::{Command as StdCommand, Stdio};
+use std::time::{Duration, Instant};
 
 const VERSION: &str = "2.5.0";
 const PUBLISH_DATE: &str = "2024-09-01";
+const DEFAULT_CACHE_TTL_SECS: u64 = 600;
+const DEFAULT_KEEP_VERSIONS: usize = 3;
+const DEFAULT_HOOK_TIMEOUT_SECS: u64 = 300;
 
 #[derive(Debug, Clone, PartialEq)]
 enum Channel {
@@ -49,6 +57,8 @@ enum Action {
     Status,
     ForceRefresh,
     UpdateGitUrl,
+    Rollback,
+    History,
 }
 
 #[derive(Debug)]
@@ -59,6 +69,13 @@ struct Config {
     launch_after: bool,
     app_name: String,
     git_url: Option<String>,
+    cache_ttl: Option<u64>,
+    offline: bool,
+    auto_offline: bool,
+    keep_versions: Option<usize>,
+    json: bool,
+    preserve: Vec<String>,
+    hook_timeout: Option<u64>,
 }
 
 impl Config {
@@ -74,6 +91,7 @@ impl Config {
         let app_name = if let Some(name) = matches.get_one::<String>("app-name") {
             name.to_string()
         } else if let Some(detected_name) = HoloMotionInstaller::detect_app_name_from_current_dir() {
+            println!("🔍 自动检测到应用名称: {}", detected_name);
             detected_name
         } else {
             "HoloMotion".to_string()
@@ -83,6 +101,16 @@ impl Config {
             .get_one::<String>("git-url")
             .or_else(|| matches.get_one::<String>("update-git-url"))
             .map(|s| s.to_string());
+        let cache_ttl = matches.get_one::<u64>("cache-ttl").copied();
+        let offline = matches.get_flag("offline");
+        let auto_offline = matches.get_flag("auto-offline");
+        let keep_versions = matches.get_one::<usize>("keep-versions").copied();
+        let json = matches.get_flag("json");
+        let preserve = matches
+            .get_many::<String>("preserve")
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default();
+        let hook_timeout = matches.get_one::<u64>("hook-timeout").copied();
 
         Ok(Config {
             action,
@@ -91,6 +119,13 @@ impl Config {
             launch_after,
             app_name,
             git_url,
+            cache_ttl,
+            offline,
+            auto_offline,
+            keep_versions,
+            json,
+            preserve,
+            hook_timeout,
         })
     }
 
@@ -119,6 +154,10 @@ impl Config {
             Ok(Action::Status)
         } else if matches.get_flag("force-refresh") {
             Ok(Action::ForceRefresh)
+        } else if matches.get_flag("rollback") {
+            Ok(Action::Rollback)
+        } else if matches.get_flag("history") {
+            Ok(Action::History)
         } else if matches.contains_id("update-git-url") {
             Ok(Action::UpdateGitUrl)
         } else {
@@ -216,15 +255,320 @@ impl PartialOrd for Version {
     }
 }
 
+/// 附注标签消息中的发布元数据 (`key: value` 行)
+#[derive(Debug, Default)]
+struct ReleaseMetadata {
+    min_installer: Option<String>,
+    arch: Vec<String>,
+    requires: Vec<String>,
+}
+
+impl ReleaseMetadata {
+    fn parse(message: &str) -> Self {
+        let mut metadata = ReleaseMetadata::default();
+        for line in message.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            let items = value
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty());
+            match key.trim().to_lowercase().as_str() {
+                "min-installer" => metadata.min_installer = Some(value.to_string()),
+                "arch" => metadata.arch.extend(items),
+                "requires" => metadata.requires.extend(items),
+                _ => {}
+            }
+        }
+        metadata
+    }
+
+    /// 返回与本机不兼容的原因，兼容时返回None
+    fn incompatibility(&self) -> Option<String> {
+        if let Some(min_installer) = &self.min_installer {
+            match (Version::parse(min_installer), Version::parse(VERSION)) {
+                (Ok(required), Ok(current)) if current < required => {
+                    return Some(format!("需要安装器版本 >= {} (当前 {})", min_installer, VERSION));
+                }
+                (Err(_), _) => return Some(format!("无法解析 min-installer: {}", min_installer)),
+                _ => {}
+            }
+        }
+
+        if !self.arch.is_empty() {
+            let current_arch = normalize_arch(std::env::consts::ARCH);
+            if !self.arch.iter().any(|arch| normalize_arch(arch) == current_arch) {
+                return Some(format!("不支持当前架构 {} (支持: {})", current_arch, self.arch.join(", ")));
+            }
+        }
+
+        if !self.requires.is_empty() {
+            let os_release = read_os_release();
+            for requirement in &self.requires {
+                if let Err(reason) = check_requirement(requirement, &os_release) {
+                    return Some(reason);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+fn normalize_arch(arch: &str) -> String {
+    match arch.trim().to_lowercase().as_str() {
+        "arm64" => "aarch64".to_string(),
+        "amd64" | "x64" => "x86_64".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn read_os_release() -> HashMap<String, String> {
+    fs::read_to_string("/etc/os-release")
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_lowercase(), value.trim().trim_matches('"').to_string()))
+        .collect()
+}
+
+/// 按点分数字比较版本号，如 22.04 与 20.10
+fn compare_dotted(a: &str, b: &str) -> Ordering {
+    let mut left = a.split('.');
+    let mut right = b.split('.');
+    loop {
+        match (left.next(), right.next()) {
+            (None, None) => return Ordering::Equal,
+            (l, r) => {
+                let l = l.unwrap_or("0");
+                let r = r.unwrap_or("0");
+                let ordering = match (l.parse::<u64>(), r.parse::<u64>()) {
+                    (Ok(l), Ok(r)) => l.cmp(&r),
+                    _ => l.cmp(r),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+        }
+    }
+}
+
+/// 把 `config/*.ini`、`data/**` 这类模式转换为匹配相对路径的正则
+fn glob_to_regex(pattern: &str) -> Result<Regex> {
+    let mut regex = String::from("^");
+    let mut chars = pattern.trim_start_matches("./").chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            other => regex.push_str(&regex::escape(&other.to_string())),
+        }
+    }
+    regex.push('$');
+    Ok(Regex::new(&regex)?)
+}
+
+/// 检查 `requires` 条件，如 `os=ubuntu`、`os-version>=22.04`，键对应 /etc/os-release 字段
+fn check_requirement(requirement: &str, os_release: &HashMap<String, String>) -> std::result::Result<(), String> {
+    let operators = [">=", "<=", "!=", "=", ">", "<"];
+    let Some((position, operator)) = operators
+        .iter()
+        .filter_map(|op| requirement.find(op).map(|pos| (pos, *op)))
+        .min_by_key(|(pos, op)| (*pos, std::cmp::Reverse(op.len())))
+    else {
+        return Err(format!("无法解析 requires 条件: {}", requirement));
+    };
+
+    let key = requirement[..position].trim().to_lowercase();
+    let expected = requirement[position + operator.len()..].trim();
+    let field = match key.as_str() {
+        "os" => "id".to_string(),
+        "os-version" => "version_id".to_string(),
+        other => other.replace('-', "_"),
+    };
+    let Some(actual) = os_release.get(&field) else {
+        return Err(format!("无法确定系统属性 {} (条件: {})", key, requirement));
+    };
+
+    let ordering = compare_dotted(actual, expected);
+    let satisfied = match operator {
+        ">=" => ordering != Ordering::Less,
+        "<=" => ordering != Ordering::Greater,
+        ">" => ordering == Ordering::Greater,
+        "<" => ordering == Ordering::Less,
+        "!=" => !actual.eq_ignore_ascii_case(expected),
+        _ => actual.eq_ignore_ascii_case(expected),
+    };
+
+    if satisfied {
+        Ok(())
+    } else {
+        Err(format!("系统不满足要求 {} (当前 {}={})", requirement, key, actual))
+    }
+}
+
+/// 标签签名格式
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SignatureFormat {
+    Gpg,
+    Ssh,
+}
+
+/// 标签信任策略: checkout前要求 `git verify-tag` 通过
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TrustPolicy {
+    format: SignatureFormat,
+    /// GPG: GNUPGHOME目录; SSH: allowed_signers文件。相对路径基于状态目录
+    keyring: PathBuf,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct ChannelSettings {
+    trust: Option<TrustPolicy>,
+}
+
+/// 应用级安装器配置 (状态目录下的 settings.json)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    trust: Option<TrustPolicy>,
+    channels: HashMap<String, ChannelSettings>,
+    /// 远程标签缓存有效期(秒)
+    cache_ttl_secs: Option<u64>,
+    /// 远程仓库不可达时自动进入离线模式
+    auto_offline: bool,
+    /// 保留的版本目录数量(含当前版本)
+    keep_versions: Option<usize>,
+    /// 升级时从旧版本带到新版本的本地文件，如 `config/*.ini`
+    preserve: Vec<String>,
+    /// 发布包中钩子脚本的超时时间(秒)
+    hook_timeout_secs: Option<u64>,
+}
+
+impl Settings {
+    fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Settings::default());
+        }
+        let content = fs::read_to_string(path)?;
+        serde_json::from_str(&content)
+            .map_err(|e| anyhow!("❌ 配置文件格式错误 {}: {}", path.display(), e))
+    }
+
+    fn trust_policy(&self, channel: &Channel) -> Option<&TrustPolicy> {
+        self.channels
+            .get(channel.as_str())
+            .and_then(|c| c.trust.as_ref())
+            .or(self.trust.as_ref())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RemoteTag {
+    name: String,
+    commit: String,
+}
+
+/// 远程标签缓存 (状态目录下的 tags-cache.json)
+#[derive(Debug, Serialize, Deserialize)]
+struct TagCache {
+    git_url: String,
+    fetched_at: chrono::DateTime<Local>,
+    tags: Vec<RemoteTag>,
+}
+
+impl TagCache {
+    fn age_secs(&self) -> u64 {
+        (Local::now() - self.fetched_at).num_seconds().max(0) as u64
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct InstalledVersion {
+    version: String,
+    channel: String,
+    installed_at: chrono::DateTime<Local>,
+}
+
+/// 已安装版本记录 (状态目录下的 versions.json)，最后一项为当前版本
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct VersionStack {
+    installed: Vec<InstalledVersion>,
+}
+
+/// 安装历史日志中的一条记录 (状态目录下的 history.jsonl)
+#[derive(Debug, Serialize, Deserialize)]
+struct HistoryEntry {
+    timestamp: chrono::DateTime<Local>,
+    action: String,
+    from_version: Option<String>,
+    to_version: Option<String>,
+    commit: Option<String>,
+    channel: Option<String>,
+    git_url: Option<String>,
+    duration_ms: u64,
+    result: String,
+    error: Option<String>,
+}
+
+/// 传给钩子脚本的环境信息
+struct HookContext<'a> {
+    old_version: Option<&'a str>,
+    new_version: Option<&'a str>,
+    channel: &'a str,
+}
+
+/// 清理或升级前备份的本地修改
+struct LocalBackup {
+    dir: PathBuf,
+    files: Vec<String>,
+}
+
+/// 最新版本解析结果，包含被跳过的较新标签及原因
+struct LatestResolution {
+    version: String,
+    skipped: Vec<(String, String)>,
+}
+
 struct HoloMotionInstaller {
     app_name: String,
     ntsport_dir: PathBuf,
     program_dir: PathBuf,
+    versions_dir: PathBuf,
     caching_dir: PathBuf,
     startup_bin: PathBuf,
     installer_bin: PathBuf,
     branch_file: PathBuf,
     git_file: PathBuf,
+    state_dir: PathBuf,
+    settings_file: PathBuf,
+    settings: Settings,
+    tag_cache_file: PathBuf,
+    version_stack_file: PathBuf,
+    history_file: PathBuf,
+    cache_ttl: u64,
+    fetched: Cell<bool>,
+    remote_tags: RefCell<Option<Vec<RemoteTag>>>,
+    offline: Cell<bool>,
+    auto_offline: bool,
+    keep_versions: usize,
+    preserve: Vec<Regex>,
+    hook_timeout: Duration,
+    target_version: RefCell<Option<String>>,
 }
 
 impl HoloMotionInstaller {
@@ -241,37 +585,75 @@ impl HoloMotionInstaller {
         None
     }
 
-    fn new(app_name: Option<&str>) -> Result<Self> {
+    fn new(config: &Config) -> Result<Self> {
         let home_dir = dirs::home_dir()
             .ok_or_else(|| anyhow!("Could not determine home directory"))?;
 
-        let app_name = if let Some(name) = app_name {
-            name.to_string()
-        } else if let Some(detected_name) = Self::detect_app_name_from_current_dir() {
-            println!("🔍 自动检测到应用名称: {}", detected_name);
-            detected_name
-        } else {
-            "HoloMotion".to_string()
-        };
+        let app_name = config.app_name.clone();
 
         let ntsport_dir = home_dir.join("local/bin/ntsports");
         let program_dir = ntsport_dir.join(&app_name);
+        let versions_dir = ntsport_dir.join(format!("{}.versions", app_name));
         let caching_dir = home_dir.join("Documents/HoloMotion_log");
 
         let startup_bin = home_dir.join("local/bin").join(&app_name);
         let installer_bin = home_dir.join("local/bin").join(format!("{}_Update", &app_name));
         let branch_file = program_dir.join("branch.txt");
         let git_file = program_dir.join("git.txt");
+        let state_dir = ntsport_dir.join(".installer").join(&app_name);
+        let settings_file = state_dir.join("settings.json");
+        let settings = Settings::load(&settings_file)?;
+        let tag_cache_file = state_dir.join("tags-cache.json");
+        let version_stack_file = state_dir.join("versions.json");
+        let history_file = state_dir.join("history.jsonl");
+        let cache_ttl = config
+            .cache_ttl
+            .or(settings.cache_ttl_secs)
+            .unwrap_or(DEFAULT_CACHE_TTL_SECS);
+        let auto_offline = config.auto_offline || settings.auto_offline;
+        let keep_versions = config
+            .keep_versions
+            .or(settings.keep_versions)
+            .unwrap_or(DEFAULT_KEEP_VERSIONS)
+            .max(1);
+        let preserve = settings
+            .preserve
+            .iter()
+            .chain(config.preserve.iter())
+            .map(|pattern| glob_to_regex(pattern))
+            .collect::<Result<Vec<_>>>()?;
+        let hook_timeout = Duration::from_secs(
+            config
+                .hook_timeout
+                .or(settings.hook_timeout_secs)
+                .unwrap_or(DEFAULT_HOOK_TIMEOUT_SECS),
+        );
 
         Ok(Self {
             app_name,
             ntsport_dir,
             program_dir,
+            versions_dir,
             caching_dir,
             startup_bin,
             installer_bin,
             branch_file,
             git_file,
+            state_dir,
+            settings_file,
+            settings,
+            tag_cache_file,
+            version_stack_file,
+            history_file,
+            cache_ttl,
+            fetched: Cell::new(false),
+            remote_tags: RefCell::new(None),
+            offline: Cell::new(config.offline),
+            auto_offline,
+            keep_versions,
+            preserve,
+            hook_timeout,
+            target_version: RefCell::new(None),
         })
     }
 
@@ -297,15 +679,15 @@ impl HoloMotionInstaller {
         Ok(log_dir)
     }
 
-    fn fix_permissions(&self) -> Result<()> {
+    fn fix_permissions(&self, dir: &Path) -> Result<()> {
         self.log("正在修正程序执行权限...");
 
         let files_to_chmod = vec![
-            self.program_dir.join("HoloMotion_Update_installer_new.sh"),
-            self.program_dir.join("NT.Client.sh"),
-            self.program_dir.join("NT.Client"),
-            self.program_dir.join("NT.Config.sh"),
-            self.program_dir.join("NT.Config"),
+            dir.join("HoloMotion_Update_installer_new.sh"),
+            dir.join("NT.Client.sh"),
+            dir.join("NT.Client"),
+            dir.join("NT.Config.sh"),
+            dir.join("NT.Config"),
         ];
 
         for file_path in files_to_chmod {
@@ -383,7 +765,58 @@ impl HoloMotionInstaller {
         true
     }
 
+    fn require_network(&self, operation: &str) -> Result<()> {
+        if self.offline.get() {
+            return Err(anyhow!("❌ 离线模式下无法{}: 该操作需要访问远程仓库", operation));
+        }
+        Ok(())
+    }
+
+    /// 不调用git，直接对远程主机做一次带超时的TCP连接，避免在断网时长时间挂起
+    fn remote_reachable(&self) -> bool {
+        let git_url = fs::read_to_string(&self.git_file)
+            .map(|content| content.trim().to_string())
+            .ok()
+            .filter(|url| !url.is_empty())
+            .or_else(|| self.get_current_remote_url().ok());
+        let Some(git_url) = git_url else {
+            return false;
+        };
+
+        let (host, port) = if let Some(path) = git_url.strip_prefix("file://") {
+            return Path::new(path).exists();
+        } else if let Some((scheme, rest)) = git_url.split_once("://") {
+            let authority = rest.split('/').next().unwrap_or("");
+            let authority = authority.rsplit('@').next().unwrap_or(authority);
+            let default_port = match scheme {
+                "http" => 80,
+                "https" => 443,
+                "git" => 9418,
+                _ => 22,
+            };
+            match authority.rsplit_once(':') {
+                Some((host, port)) => (host.to_string(), port.parse().unwrap_or(default_port)),
+                None => (authority.to_string(), default_port),
+            }
+        } else if let Some((user_host, _)) = git_url.split_once(':') {
+            (user_host.rsplit('@').next().unwrap_or(user_host).to_string(), 22)
+        } else {
+            return Path::new(&git_url).exists();
+        };
+
+        let Ok(addresses) = (host.as_str(), port).to_socket_addrs() else {
+            return false;
+        };
+        addresses
+            .into_iter()
+            .any(|address| TcpStream::connect_timeout(&address, Duration::from_secs(3)).is_ok())
+    }
+
     fn test_git_connectivity(&self, git_url: &str) -> Result<bool> {
+        if self.offline.get() {
+            self.log("📴 离线模式，跳过Git仓库连通性测试");
+            return Ok(false);
+        }
         self.log(&format!("正在测试Git仓库连通性: {}", git_url));
 
         let output = StdCommand::new("git")
@@ -461,6 +894,7 @@ impl HoloMotionInstaller {
 
     fn update_git_url(&self, new_git_url: &str) -> Result<()> {
         self.log(&format!("🔄 强制更新Git仓库地址: {}", new_git_url));
+        self.require_network("更新Git仓库地址")?;
 
         if !self.is_valid_git_url(new_git_url) {
             return Err(anyhow!("❌ 无效的Git URL格式: {}", new_git_url));
@@ -566,6 +1000,10 @@ impl HoloMotionInstaller {
         if !self.repos_exist() {
             return Err(anyhow!("Repository does not exist"));
         }
+        if self.fetched.get() {
+            return Ok(());
+        }
+        self.require_network("获取远程仓库信息")?;
 
         self.log("🔄 正在获取远程仓库最新信息...");
 
@@ -605,33 +1043,269 @@ impl HoloMotionInstaller {
             self.log(&format!("🏷️ 最新标签: {:?}", latest_tags));
         }
 
+        self.fetched.set(true);
+        Ok(())
+    }
+
+    fn load_tag_cache(&self) -> Option<TagCache> {
+        let content = fs::read_to_string(&self.tag_cache_file).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    fn save_tag_cache(&self, cache: &TagCache) -> Result<()> {
+        fs::create_dir_all(&self.state_dir)?;
+        fs::write(&self.tag_cache_file, serde_json::to_string_pretty(cache)?)?;
         Ok(())
     }
 
-    fn clean_git_state(&self) -> Result<()> {
+    fn invalidate_tag_cache(&self) {
+        let _ = fs::remove_file(&self.tag_cache_file);
+        self.remote_tags.replace(None);
+    }
+
+    /// 每次运行只解析一次远程标签；缓存未过期时直接复用，网络不可用时回退到过期缓存
+    fn resolve_remote_tags(&self) -> Result<Vec<RemoteTag>> {
+        if let Some(tags) = self.remote_tags.borrow().as_ref() {
+            return Ok(tags.clone());
+        }
+
+        let git_url = self.get_current_remote_url().unwrap_or_default();
+        let cache = self.load_tag_cache().filter(|cache| cache.git_url == git_url);
+
+        if self.offline.get() {
+            let cache = cache.ok_or_else(|| anyhow!("离线模式且没有可用的标签缓存，仅使用本地标签"))?;
+            self.log(&format!("📴 离线模式，使用 {} 秒前的标签缓存", cache.age_secs()));
+            self.remote_tags.replace(Some(cache.tags.clone()));
+            return Ok(cache.tags);
+        }
+
+        if let Some(cache) = &cache
+            && cache.age_secs() < self.cache_ttl
+        {
+            self.log(&format!("💾 使用缓存的远程标签 ({} 秒前, 共 {} 个)", cache.age_secs(), cache.tags.len()));
+            self.remote_tags.replace(Some(cache.tags.clone()));
+            return Ok(cache.tags.clone());
+        }
+
+        self.log("📡 正在使用 git ls-remote 获取远程标签");
+        let output = StdCommand::new("git")
+            .args(["ls-remote", "--tags", "origin"])
+            .current_dir(&self.program_dir)
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return match cache {
+                Some(cache) => {
+                    self.log(&format!("⚠ 无法访问远程仓库，使用 {} 秒前的缓存: {}", cache.age_secs(), stderr.trim()));
+                    self.remote_tags.replace(Some(cache.tags.clone()));
+                    Ok(cache.tags)
+                }
+                None => Err(anyhow!("❌ 获取远程标签失败: {}", stderr)),
+            };
+        }
+
+        let mut tags: Vec<RemoteTag> = Vec::new();
+        for line in String::from_utf8(output.stdout)?.lines() {
+            let Some((sha, reference)) = line.split_once('\t') else {
+                continue;
+            };
+            let Some(name) = reference.strip_prefix("refs/tags/") else {
+                continue;
+            };
+            // 附注标签的 ^{} 行给出实际指向的提交
+            if let Some(name) = name.strip_suffix("^{}") {
+                if let Some(tag) = tags.iter_mut().find(|tag| tag.name == name) {
+                    tag.commit = sha.to_string();
+                }
+            } else {
+                tags.push(RemoteTag {
+                    name: name.to_string(),
+                    commit: sha.to_string(),
+                });
+            }
+        }
+
+        let cache = TagCache {
+            git_url,
+            fetched_at: Local::now(),
+            tags,
+        };
+        if let Err(e) = self.save_tag_cache(&cache) {
+            self.log(&format!("⚠ 无法写入标签缓存: {}", e));
+        }
+        self.remote_tags.replace(Some(cache.tags.clone()));
+        Ok(cache.tags)
+    }
+
+    /// 确保标签对象在本地存在，缺失时单独拉取
+    fn ensure_local_tag(&self, tag: &str) {
+        let reference = format!("refs/tags/{}", tag);
+        let exists = StdCommand::new("git")
+            .args(["rev-parse", "-q", "--verify", &reference])
+            .current_dir(&self.program_dir)
+            .output()
+            .map(|output| output.status.success())
+            .unwrap_or(false);
+        if !exists && !self.offline.get() {
+            let _ = StdCommand::new("git")
+                .args(["fetch", "origin", &format!("+{}:{}", reference, reference)])
+                .current_dir(&self.program_dir)
+                .output();
+        }
+    }
+
+    fn clean_git_state(&self, dir: &Path) -> Result<()> {
         self.log("🧹 正在清理Git工作目录状态...");
+        self.backup_local_changes(dir, "clean")?;
 
         let _ = StdCommand::new("git")
-            .args(&["reset", "--hard", "HEAD"])
-            .current_dir(&self.program_dir)
+            .args(["reset", "--hard", "HEAD"])
+            .current_dir(dir)
             .output();
 
         let _ = StdCommand::new("git")
-            .args(&["clean", "-fd"])
-            .current_dir(&self.program_dir)
+            .args(["clean", "-fd"])
+            .current_dir(dir)
             .output();
 
         let _ = StdCommand::new("git")
-            .args(&["checkout", "."])
-            .current_dir(&self.program_dir)
+            .args(["checkout", "."])
+            .current_dir(dir)
             .output();
 
+        if dir.join(".gitmodules").exists() {
+            self.log("🧹 正在清理子模块工作目录状态...");
+            let _ = StdCommand::new("git")
+                .args(["submodule", "foreach", "--recursive", "git reset --hard HEAD && git clean -fd"])
+                .current_dir(dir)
+                .output();
+        }
+
         self.log("✅ Git工作目录状态清理完成");
         Ok(())
     }
 
+    fn is_preserved(&self, path: &str) -> bool {
+        self.preserve.iter().any(|pattern| pattern.is_match(path))
+    }
+
+    /// 列出工作目录中被修改和未跟踪的文件；被忽略的文件只在匹配保留列表时列出
+    fn local_changes(&self, dir: &Path) -> Vec<String> {
+        let Ok(output) = StdCommand::new("git")
+            .args(["-c", "core.fileMode=false", "status", "--porcelain", "-z", "--untracked-files=all", "--ignored"])
+            .current_dir(dir)
+            .output()
+        else {
+            return Vec::new();
+        };
+        if !output.status.success() {
+            return Vec::new();
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut files = Vec::new();
+        let mut entries = stdout.split('\0');
+        while let Some(entry) = entries.next() {
+            if entry.len() < 4 {
+                continue;
+            }
+            let (status, path) = entry.split_at(3);
+            if status.starts_with('R') || status.starts_with('C') {
+                // 重命名记录后面跟着原路径
+                entries.next();
+            }
+            if path == "branch.txt" || path == "git.txt" {
+                continue;
+            }
+            if status.starts_with("!!") && !self.is_preserved(path) {
+                continue;
+            }
+            if dir.join(path).is_file() {
+                files.push(path.to_string());
+            }
+        }
+        files
+    }
+
+    /// 把本地修改备份到状态目录下带时间戳的目录中
+    fn backup_local_changes(&self, dir: &Path, label: &str) -> Result<Option<LocalBackup>> {
+        let files = self.local_changes(dir);
+        if files.is_empty() {
+            return Ok(None);
+        }
+
+        let backup_dir = self
+            .state_dir
+            .join("backups")
+            .join(format!("{}-{}", Local::now().format("%Y%m%d-%H%M%S%.3f"), label));
+        self.log(&format!("💾 检测到 {} 个本地修改的文件，备份到: {}", files.len(), backup_dir.display()));
+        for file in &files {
+            let target = backup_dir.join(file);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(dir.join(file), &target)?;
+            self.log(&format!("  📄 {}", file));
+        }
+
+        Ok(Some(LocalBackup { dir: backup_dir, files }))
+    }
+
+    /// 把备份中匹配保留列表的文件恢复到新的工作目录，报告每个被覆盖的文件
+    fn restore_preserved(&self, backup: &LocalBackup, dir: &Path) -> Result<()> {
+        for file in backup.files.iter().filter(|file| self.is_preserved(file)) {
+            let source = backup.dir.join(file);
+            let target = dir.join(file);
+            if target.exists() {
+                if fs::read(&source)? == fs::read(&target)? {
+                    continue;
+                }
+                self.log(&format!("⚠ 保留本地文件，覆盖新版本中的: {}", file));
+            } else {
+                self.log(&format!("♻️ 恢复本地文件: {}", file));
+            }
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(&source, &target)?;
+        }
+        Ok(())
+    }
+
+    /// 初始化子模块并同步到当前提交记录的版本
+    fn update_submodules(&self, dir: &Path) -> Result<()> {
+        if !dir.join(".gitmodules").exists() {
+            return Ok(());
+        }
+
+        self.log("📦 正在同步子模块...");
+
+        let output = StdCommand::new("git")
+            .args(["submodule", "sync", "--recursive"])
+            .current_dir(dir)
+            .output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            self.log(&format!("⚠ 子模块地址同步失败: {}", stderr));
+        }
+
+        let output = StdCommand::new("git")
+            .args(["submodule", "update", "--init", "--recursive", "--force"])
+            .current_dir(dir)
+            .output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow!("❌ 子模块更新失败: {}", stderr));
+        }
+
+        self.log("✅ 子模块已同步到记录的版本");
+        Ok(())
+    }
+
     fn force_refresh_tags(&self) -> Result<()> {
         self.log("🔄 强制刷新远程标签信息...");
+        self.require_network("刷新远程标签")?;
 
         if !self.repos_exist() {
             return Err(anyhow!("Repository does not exist"));
@@ -665,7 +1339,10 @@ impl HoloMotionInstaller {
             return Err(anyhow!("❌ Failed to fetch tags: {}", stderr));
         }
 
-        self.log("✅ 远程标签刷新完成");
+        self.invalidate_tag_cache();
+        self.fetched.set(true);
+        let tags = self.resolve_remote_tags()?;
+        self.log(&format!("✅ 远程标签刷新完成，共 {} 个标签", tags.len()));
         Ok(())
     }
 
@@ -694,7 +1371,6 @@ impl HoloMotionInstaller {
         self.log("ℹ 配置文件不存在，根据仓库标签判断通道");
 
         self.ensure_correct_remote(provided_git_url)?;
-        self.fetch_remote()?;
 
         let output = StdCommand::new("git")
             .args(&["describe", "--tags"])
@@ -781,37 +1457,35 @@ impl HoloMotionInstaller {
     }
 
     fn get_latest_version(&self, channel: &Channel, provided_git_url: Option<&str>) -> Result<String> {
+        Ok(self.resolve_latest_version(channel, provided_git_url)?.version)
+    }
+
+    fn resolve_latest_version(&self, channel: &Channel, provided_git_url: Option<&str>) -> Result<LatestResolution> {
         self.log("🔍 获取最新版版本号");
         self.assert_repos_exist()?;
         self.log(&format!("📍 当前通道: {}", channel.as_str()));
 
         self.ensure_correct_remote(provided_git_url)?;
-        self.fetch_remote()?;
-
-        self.log("📡 方法1: 使用 git ls-remote 获取远程标签");
-        let output = StdCommand::new("git")
-            .args(&["ls-remote", "--tags", "--refs", "origin"])
-            .current_dir(&self.program_dir)
-            .output()?;
 
+        self.log("📡 方法1: 使用远程标签列表");
         let mut versions_method1 = Vec::new();
-        if output.status.success() {
-            let tags_output = String::from_utf8(output.stdout)?;
-            for line in tags_output.lines() {
-                if let Some(tag_part) = line.split("refs/tags/").nth(1) {
-                    if let Ok(version_str) = self.extract_version_from_git_describe(tag_part) {
-                        if let Ok(version) = Version::parse(&version_str) {
-                            let should_include = match channel {
-                                Channel::Release => version.is_release() || version.is_date_version(),
-                                Channel::Master => true,
-                            };
-                            if should_include {
-                                versions_method1.push(version);
-                            }
+        match self.resolve_remote_tags() {
+            Ok(tags) => {
+                for tag in &tags {
+                    if let Ok(version_str) = self.extract_version_from_git_describe(&tag.name)
+                        && let Ok(version) = Version::parse(&version_str)
+                    {
+                        let should_include = match channel {
+                            Channel::Release => version.is_release() || version.is_date_version(),
+                            Channel::Master => true,
+                        };
+                        if should_include {
+                            versions_method1.push(version);
                         }
                     }
                 }
             }
+            Err(e) => self.log(&format!("⚠ {}", e)),
         }
 
         self.log("💾 方法2: 使用本地标签列表作为备选");
@@ -851,10 +1525,108 @@ impl HoloMotionInstaller {
         }
 
         versions.sort();
-        let latest = versions.last().unwrap();
+        versions.dedup();
         self.log(&format!("📈 找到 {} 个有效版本", versions.len()));
-        self.log(&format!("🎯 远端最新版本: {}", latest.raw));
-        Ok(latest.raw.clone())
+
+        let mut skipped = Vec::new();
+        for candidate in versions.iter().rev() {
+            match self.tag_rejection(channel, &candidate.raw) {
+                Some(reason) => {
+                    self.log(&format!("⛔ 跳过标签 {}: {}", candidate.raw, reason));
+                    skipped.push((candidate.raw.clone(), reason));
+                }
+                None => {
+                    self.log(&format!("🎯 远端最新版本: {}", candidate.raw));
+                    return Ok(LatestResolution {
+                        version: candidate.raw.clone(),
+                        skipped,
+                    });
+                }
+            }
+        }
+
+        Err(anyhow!("❌ 通道 {} 的 {} 个版本均未通过检查", channel.as_str(), versions.len()))
+    }
+
+    /// 返回标签不可用的原因，可用时返回None
+    fn tag_rejection(&self, channel: &Channel, tag: &str) -> Option<String> {
+        self.ensure_local_tag(tag);
+        if let Some(policy) = self.settings.trust_policy(channel)
+            && let Err(reason) = self.verify_tag(tag, policy)
+        {
+            return Some(reason);
+        }
+        self.read_release_metadata(tag)
+            .and_then(|metadata| metadata.incompatibility())
+    }
+
+    /// 读取附注标签消息中的发布元数据，轻量标签返回None
+    fn read_release_metadata(&self, tag: &str) -> Option<ReleaseMetadata> {
+        let output = StdCommand::new("git")
+            .args([
+                "for-each-ref",
+                "--format=%(objecttype)%00%(contents:subject)%0a%(contents:body)",
+                &format!("refs/tags/{}", tag),
+            ])
+            .current_dir(&self.program_dir)
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let (object_type, message) = stdout.split_once('\0')?;
+        if object_type != "tag" {
+            return None;
+        }
+        Some(ReleaseMetadata::parse(message))
+    }
+
+    fn verify_tag(&self, tag: &str, policy: &TrustPolicy) -> std::result::Result<(), String> {
+        let keyring = if policy.keyring.is_absolute() {
+            policy.keyring.clone()
+        } else {
+            self.state_dir.join(&policy.keyring)
+        };
+        if !keyring.exists() {
+            return Err(format!("信任密钥环不存在: {}", keyring.display()));
+        }
+
+        let mut command = StdCommand::new("git");
+        match policy.format {
+            SignatureFormat::Gpg => {
+                command.env("GNUPGHOME", &keyring);
+            }
+            SignatureFormat::Ssh => {
+                command.arg("-c").arg(format!("gpg.ssh.allowedSignersFile={}", keyring.display()));
+            }
+        }
+        let output = command
+            .args(["verify-tag", tag])
+            .current_dir(&self.program_dir)
+            .output()
+            .map_err(|e| format!("无法执行 git verify-tag: {}", e))?;
+
+        if output.status.success() {
+            return Ok(());
+        }
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("no signature found") || stderr.contains("cannot verify a non-tag object") {
+            Err("标签未签名".to_string())
+        } else {
+            Err(format!("签名不可信: {}", stderr.lines().next().unwrap_or("").trim()))
+        }
+    }
+
+    fn assert_tag_trusted(&self, channel: &Channel, tag: &str) -> Result<()> {
+        if let Some(policy) = self.settings.trust_policy(channel) {
+            self.verify_tag(tag, policy)
+                .map_err(|reason| anyhow!("❌ 标签 {} 未通过签名验证: {}", tag, reason))?;
+            self.log(&format!("🔐 标签签名验证通过: {}", tag));
+        }
+        Ok(())
     }
 
     fn kill_process(&self, pid: u32) -> Result<()> {
@@ -876,11 +1648,19 @@ impl HoloMotionInstaller {
     fn clean_installed(&self) -> Result<()> {
         self.log("🧹 开始清理");
 
-        if self.program_dir.exists() {
+        if self.program_dir_is_link() {
+            fs::remove_file(&self.program_dir)?;
+            self.log("✅ 已清理程序目录链接");
+        } else if self.program_dir.exists() {
             fs::remove_dir_all(&self.program_dir)?;
             self.log("✅ 已清理程序目录");
         }
 
+        if self.versions_dir.exists() {
+            fs::remove_dir_all(&self.versions_dir)?;
+            self.log("✅ 已清理版本目录");
+        }
+
         if self.startup_bin.exists() {
             fs::remove_file(&self.startup_bin)?;
             self.log("✅ 已清理启动脚本");
@@ -903,18 +1683,20 @@ impl HoloMotionInstaller {
     fn install(&self, channel: &Channel, provided_git_url: Option<&str>) -> Result<()> {
         self.log("🚀 开始安装");
         self.log(&format!("📍 应用: {}, 通道: {}", self.app_name, channel.as_str()));
+        self.require_network("安装")?;
 
         let git_url = self.get_git_url(provided_git_url)?;
         self.log(&format!("🔗 使用Git仓库: {}", git_url));
 
         self.clean_installed()?;
 
-        fs::create_dir_all(&self.ntsport_dir)?;
-        self.log(&format!("📁 创建程序安装目录: {:?}", self.ntsport_dir));
+        fs::create_dir_all(&self.versions_dir)?;
+        self.log(&format!("📁 创建程序安装目录: {:?}", self.versions_dir));
 
         self.log("⬇️ 正在下载程序");
+        let bootstrap_dir = self.versions_dir.join(".bootstrap");
         let output = StdCommand::new("git")
-            .args(&["clone", &git_url, &self.program_dir.to_string_lossy()])
+            .args(["clone", "--no-checkout", &git_url, &bootstrap_dir.to_string_lossy()])
             .current_dir(&self.ntsport_dir)
             .output()?;
 
@@ -922,42 +1704,423 @@ impl HoloMotionInstaller {
             let stderr = String::from_utf8_lossy(&output.stderr);
             return Err(anyhow!("❌ Failed to clone repository: {}\n错误详情: {}", git_url, stderr));
         }
+        self.switch_to_version_dir(&bootstrap_dir)?;
 
-        let _ = StdCommand::new("git")
-            .args(&["config", "--global", "--add", "safe.directory", &self.program_dir.to_string_lossy()])
-            .output();
+        let result = self.install_from_bootstrap(channel, provided_git_url, &git_url);
+        if result.is_err() {
+            self.log("⚠ 安装失败，正在清理未完成的安装");
+            let _ = fs::remove_file(&self.program_dir);
+        }
+        let _ = fs::remove_dir_all(&bootstrap_dir);
+
+        let latest_version = result?;
+        self.log(&format!("🎉 安装完成! 版本: {}", latest_version));
+        Ok(())
+    }
 
+    /// 在临时克隆中解析最新版本，然后按升级流程准备并切换到版本目录
+    fn install_from_bootstrap(&self, channel: &Channel, provided_git_url: Option<&str>, git_url: &str) -> Result<String> {
         if provided_git_url.is_some() && !self.git_file.exists() {
-            self.save_git_url(&git_url)?;
+            self.save_git_url(git_url)?;
         }
 
         self.fetch_remote()?;
         let latest_version = self.get_latest_version(channel, provided_git_url)?;
-        self.log(&format!("🔄 正在切换到版本: {}", latest_version));
+        self.target_version.replace(Some(latest_version.clone()));
+        self.assert_tag_trusted(channel, &latest_version)?;
+
+        let version_dir = self.prepare_version_dir(channel, &latest_version)?;
+        let hook_context = HookContext {
+            old_version: None,
+            new_version: Some(&latest_version),
+            channel: channel.as_str(),
+        };
+        self.run_hook("pre-install", &version_dir, &hook_context)?;
 
+        self.switch_to_version_dir(&version_dir)?;
+        self.create_symlinks()?;
+        self.record_installed_version(None, &latest_version, channel);
+
+        if let Err(e) = self.run_hook("post-install", &self.program_dir, &hook_context) {
+            self.log(&format!("⚠ {}", e));
+        }
+
+        Ok(latest_version)
+    }
+
+    fn program_dir_is_link(&self) -> bool {
+        fs::symlink_metadata(&self.program_dir)
+            .map(|metadata| metadata.file_type().is_symlink())
+            .unwrap_or(false)
+    }
+
+    fn mark_safe_directory(&self, dir: &Path) {
+        let dir = dir.to_string_lossy();
+        let configured = StdCommand::new("git")
+            .args(["config", "--global", "--get-all", "safe.directory"])
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).lines().any(|line| line == dir))
+            .unwrap_or(false);
+        if !configured {
+            let _ = StdCommand::new("git")
+                .args(["config", "--global", "--add", "safe.directory", &dir])
+                .output();
+        }
+    }
+
+    /// 运行发布包 `hooks/` 目录中的钩子脚本，不存在时直接返回
+    fn run_hook(&self, name: &str, dir: &Path, context: &HookContext) -> Result<()> {
+        let Some(script) = [dir.join("hooks").join(name), dir.join("hooks").join(format!("{}.sh", name))]
+            .into_iter()
+            .find(|path| path.is_file())
+        else {
+            return Ok(());
+        };
+
+        self.log(&format!("🪝 执行钩子 {}: {}", name, script.display()));
+        fs::create_dir_all(&self.state_dir)?;
+        let output_file = self.state_dir.join(format!("hook-{}.log", name));
+        let stdout = fs::File::create(&output_file)?;
+        let stderr = stdout.try_clone()?;
+
+        #[cfg(unix)]
+        let executable = {
+            use std::os::unix::fs::PermissionsExt;
+            fs::metadata(&script)?.permissions().mode() & 0o111 != 0
+        };
+        #[cfg(not(unix))]
+        let executable = false;
+        let mut command = if executable {
+            StdCommand::new(&script)
+        } else {
+            let mut command = StdCommand::new("sh");
+            command.arg(&script);
+            command
+        };
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+
+        let mut child = command
+            .current_dir(dir)
+            .env("HOLOMOTION_HOOK", name)
+            .env("HOLOMOTION_APP_NAME", &self.app_name)
+            .env("HOLOMOTION_OLD_VERSION", context.old_version.unwrap_or(""))
+            .env("HOLOMOTION_NEW_VERSION", context.new_version.unwrap_or(""))
+            .env("HOLOMOTION_CHANNEL", context.channel)
+            .env("HOLOMOTION_PROGRAM_DIR", &self.program_dir)
+            .env("HOLOMOTION_VERSION_DIR", dir)
+            .env("HOLOMOTION_STARTUP_BIN", &self.startup_bin)
+            .env("HOLOMOTION_STATE_DIR", &self.state_dir)
+            .env("HOLOMOTION_DATA_DIR", &self.caching_dir)
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr)
+            .spawn()?;
+
+        let started = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break Some(status);
+            }
+            if started.elapsed() >= self.hook_timeout {
+                // 钩子在独立进程组中运行，超时时连同其子进程一起结束
+                let _ = StdCommand::new("kill")
+                    .args(["-9", &format!("-{}", child.id())])
+                    .output();
+                let _ = child.kill();
+                let _ = child.wait();
+                break None;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        };
+
+        for line in fs::read_to_string(&output_file).unwrap_or_default().lines() {
+            self.log(&format!("  │ {}", line));
+        }
+
+        match status {
+            Some(status) if status.success() => {
+                self.log(&format!("✅ 钩子 {} 执行成功", name));
+                Ok(())
+            }
+            Some(status) => Err(anyhow!("❌ 钩子 {} 执行失败: {}", name, status)),
+            None => Err(anyhow!("❌ 钩子 {} 执行超时 ({} 秒)", name, self.hook_timeout.as_secs())),
+        }
+    }
+
+    /// 在工作目录中切换到指定版本，依次尝试多种方式
+    fn checkout_version(&self, dir: &Path, version: &str) -> Result<()> {
+        self.log(&format!("🔄 正在切换到版本: {}", version));
+        // **修复生命周期问题：预先创建字符串变量**
+        let fetch_refspec = format!("refs/tags/{}:refs/tags/{}", version, version);
+        let tag_ref = format!("tags/{}", version);
+
+        let mut success = false;
+
+        // **方法1: 直接checkout**
+        self.log("🔄 尝试方法1: checkout");
         let output = StdCommand::new("git")
-            .args(&["checkout", &latest_version])
-            .current_dir(&self.program_dir)
+            .args(["checkout", "--force", version])
+            .current_dir(dir)
             .output()?;
 
-        if !output.status.success() {
+        if output.status.success() {
+            success = true;
+            self.log("✅ 使用checkout方式切换版本成功");
+        } else if !self.offline.get() {
+            // **方法2: fetch特定tag然后reset**
+            self.log("🔄 尝试方法2: fetch+reset");
             let output = StdCommand::new("git")
-                .args(&["reset", "--hard", &latest_version])
-                .current_dir(&self.program_dir)
+                .args(["fetch", "origin", &fetch_refspec])
+                .current_dir(dir)
+                .output()?;
+            if output.status.success() {
+                let reset_output = StdCommand::new("git")
+                    .args(["reset", "--hard", version])
+                    .current_dir(dir)
+                    .output()?;
+
+                if reset_output.status.success() {
+                    success = true;
+                    self.log("✅ 使用fetch+reset方式切换版本成功");
+                }
+            }
+
+            if !success {
+                // **方法3: fetch all然后reset**
+                self.log("🔄 尝试方法3: fetch-all+reset");
+                let output = StdCommand::new("git")
+                    .args(["fetch", "--all"])
+                    .current_dir(dir)
+                    .output()?;
+
+                if output.status.success() {
+                    let reset_output = StdCommand::new("git")
+                        .args(["reset", "--hard", version])
+                        .current_dir(dir)
+                        .output();
+
+                    if reset_output.is_ok_and(|output| output.status.success()) {
+                        success = true;
+                        self.log("✅ 使用fetch-all+reset方式切换版本成功");
+                    }
+                }
+            }
+        }
+
+        if !success {
+            // **方法4: 最后尝试使用tags路径**
+            self.log("🔄 尝试方法4: tags路径");
+            let output = StdCommand::new("git")
+                .args(["reset", "--hard", &tag_ref])
+                .current_dir(dir)
+                .output();
+
+            if output.is_ok_and(|output| output.status.success()) {
+                success = true;
+                self.log("✅ 使用tags方式切换版本成功");
+            }
+        }
+
+        if !success {
+            return Err(anyhow!("❌ 所有版本切换方式都失败了"));
+        }
+        Ok(())
+    }
+
+    /// 在独立的版本目录中准备指定版本，全部完成前不会影响正在使用的程序目录
+    fn prepare_version_dir(&self, channel: &Channel, version: &str) -> Result<PathBuf> {
+        let version_dir = self.versions_dir.join(version);
+        let current_dir = fs::canonicalize(&self.program_dir)?;
+        if fs::canonicalize(&version_dir).is_ok_and(|dir| dir == current_dir) {
+            return Err(anyhow!("❌ 版本 {} 正在使用中", version));
+        }
+        fs::create_dir_all(&self.versions_dir)?;
+        let backup = self.backup_local_changes(&current_dir, "upgrade")?;
+
+        let staging_dir = self.versions_dir.join(format!(".{}.partial", version));
+        let work_dir = if version_dir.join(".git").exists() {
+            self.log(&format!("♻️ 复用已保留的版本目录: {}", version_dir.display()));
+            self.clean_git_state(&version_dir)?;
+            version_dir.clone()
+        } else {
+            if staging_dir.exists() {
+                fs::remove_dir_all(&staging_dir)?;
+            }
+            self.log(&format!("📁 正在准备版本目录: {}", version_dir.display()));
+            let output = StdCommand::new("git")
+                .args(["clone", "--local", "--no-checkout"])
+                .arg(&current_dir)
+                .arg(&staging_dir)
                 .output()?;
             if !output.status.success() {
                 let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(anyhow!("❌ 切换到最新版本失败: {}", stderr));
+                return Err(anyhow!("❌ 创建版本目录失败: {}", stderr));
             }
-        }
 
-        self.create_symlinks()?;
-        self.fix_permissions()?;
+            let origin_url = self.get_current_remote_url()?;
+            let output = StdCommand::new("git")
+                .args(["remote", "set-url", "origin", &origin_url])
+                .current_dir(&staging_dir)
+                .output()?;
+            if !output.status.success() {
+                return Err(anyhow!("Failed to update remote origin URL"));
+            }
+            staging_dir.clone()
+        };
 
-        fs::write(&self.branch_file, channel.as_str())?;
+        let prepared = self.checkout_version(&work_dir, version)
+            .and_then(|_| self.update_submodules(&work_dir))
+            .and_then(|_| match &backup {
+                Some(backup) => self.restore_preserved(backup, &work_dir),
+                None => Ok(()),
+            })
+            .and_then(|_| self.fix_permissions(&work_dir));
+        if let Err(e) = prepared {
+            if work_dir == staging_dir {
+                let _ = fs::remove_dir_all(&staging_dir);
+            }
+            return Err(e);
+        }
+
+        if self.git_file.exists() {
+            fs::copy(&self.git_file, work_dir.join("git.txt"))?;
+        }
+        fs::write(work_dir.join("branch.txt"), channel.as_str())?;
         self.log(&format!("💾 写入配置文件: channel={}", channel.as_str()));
 
-        self.log(&format!("🎉 安装完成! 版本: {}", latest_version));
+        if work_dir == staging_dir {
+            if version_dir.exists() {
+                fs::remove_dir_all(&version_dir)?;
+            }
+            fs::rename(&staging_dir, &version_dir)?;
+        }
+        self.mark_safe_directory(&version_dir);
+
+        Ok(version_dir)
+    }
+
+    /// 通过临时符号链接加rename原子地把程序目录指向新的版本目录
+    fn switch_to_version_dir(&self, version_dir: &Path) -> Result<()> {
+        let switch_link = self.ntsport_dir.join(format!(".{}.switch", self.app_name));
+        if fs::symlink_metadata(&switch_link).is_ok() {
+            fs::remove_file(&switch_link)?;
+        }
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(version_dir, &switch_link)?;
+        fs::rename(&switch_link, &self.program_dir)?;
+
+        self.log(&format!("🔀 程序目录已切换到: {}", version_dir.display()));
+        Ok(())
+    }
+
+    /// 把旧版的原地安装目录迁移为版本目录
+    fn migrate_legacy_layout(&self, current_version: &str) -> Result<()> {
+        if self.program_dir_is_link() || !self.program_dir.exists() {
+            return Ok(());
+        }
+
+        let version_dir = self.versions_dir.join(current_version);
+        if version_dir.exists() {
+            return Err(anyhow!("❌ 无法迁移程序目录，目标已存在: {}", version_dir.display()));
+        }
+
+        self.log(&format!("📦 正在迁移程序目录到版本目录: {}", version_dir.display()));
+        fs::create_dir_all(&self.versions_dir)?;
+        fs::rename(&self.program_dir, &version_dir)?;
+        self.switch_to_version_dir(&version_dir)?;
+        self.mark_safe_directory(&version_dir);
+        Ok(())
+    }
+
+    fn load_version_stack(&self) -> VersionStack {
+        fs::read_to_string(&self.version_stack_file)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn save_version_stack(&self, stack: &VersionStack) -> Result<()> {
+        fs::create_dir_all(&self.state_dir)?;
+        fs::write(&self.version_stack_file, serde_json::to_string_pretty(stack)?)?;
+        Ok(())
+    }
+
+    /// 记录新切换到的版本；旧安装没有记录时先补上切换前的版本
+    fn record_installed_version(&self, previous: Option<(&str, &str)>, version: &str, channel: &Channel) {
+        let mut stack = self.load_version_stack();
+        if let Some((previous_version, previous_channel)) = previous
+            && stack.installed.last().is_none_or(|entry| entry.version != previous_version)
+        {
+            stack.installed.push(InstalledVersion {
+                version: previous_version.to_string(),
+                channel: previous_channel.to_string(),
+                installed_at: Local::now(),
+            });
+        }
+        stack.installed.push(InstalledVersion {
+            version: version.to_string(),
+            channel: channel.as_str().to_string(),
+            installed_at: Local::now(),
+        });
+
+        if let Err(e) = self.save_version_stack(&stack) {
+            self.log(&format!("⚠ 无法保存版本记录: {}", e));
+        }
+    }
+
+    /// 删除多余的旧版本目录，优先保留版本记录中最近使用过的版本
+    fn prune_versions(&self) {
+        let current_dir = fs::canonicalize(&self.program_dir).ok();
+        let Ok(entries) = fs::read_dir(&self.versions_dir) else {
+            return;
+        };
+        let stack = self.load_version_stack();
+
+        let mut old_dirs: Vec<_> = entries
+            .flatten()
+            .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+            .map(|entry| entry.path())
+            .filter(|path| fs::canonicalize(path).ok() != current_dir)
+            .map(|path| {
+                let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                let recency = stack
+                    .installed
+                    .iter()
+                    .rev()
+                    .position(|entry| entry.version == name)
+                    .unwrap_or(usize::MAX);
+                let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+                ((recency, std::cmp::Reverse(modified)), path)
+            })
+            .collect();
+        old_dirs.sort_by_key(|(order, _)| *order);
+
+        for (_, path) in old_dirs.into_iter().skip(self.keep_versions - 1) {
+            match fs::remove_dir_all(&path) {
+                Ok(_) => self.log(&format!("🗑️ 删除旧版本目录: {}", path.display())),
+                Err(e) => self.log(&format!("⚠ 删除旧版本目录失败 {}: {}", path.display(), e)),
+            }
+        }
+    }
+
+    /// 先写临时链接再rename，保证链接始终指向完整的目标
+    fn replace_symlink(&self, target: &Path, link: &Path) -> Result<()> {
+        let mut temp_name = link.as_os_str().to_os_string();
+        temp_name.push(".tmp");
+        let temp_link = PathBuf::from(temp_name);
+        if fs::symlink_metadata(&temp_link).is_ok() {
+            fs::remove_file(&temp_link)?;
+        }
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(target, &temp_link)?;
+        fs::rename(&temp_link, link)?;
         Ok(())
     }
 
@@ -968,39 +2131,25 @@ impl HoloMotionInstaller {
         let install_app = self.ntsport_dir.join("HoloMotion_Update_installer_new.sh");
 
         if install_src.exists() {
-            fs::copy(&install_src, &install_app)?;
+            let install_tmp = self.ntsport_dir.join("HoloMotion_Update_installer_new.sh.tmp");
+            fs::copy(&install_src, &install_tmp)?;
+            fs::rename(&install_tmp, &install_app)?;
             self.log(&format!("📋 复制安装脚本: {}", install_app.display()));
 
             let _ = StdCommand::new("chmod")
-                .args(&["+x", install_app.to_string_lossy().as_ref()])
+                .args(["+x", install_app.to_string_lossy().as_ref()])
                 .output();
 
-            if self.installer_bin.exists() {
-                let _ = fs::remove_file(&self.installer_bin);
-            }
-
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::symlink;
-                if let Ok(_) = symlink(&install_app, &self.installer_bin) {
-                    self.log(&format!("🔗 创建安装器符号链接: {}", self.installer_bin.display()));
-                }
+            if self.replace_symlink(&install_app, &self.installer_bin).is_ok() {
+                self.log(&format!("🔗 创建安装器符号链接: {}", self.installer_bin.display()));
             }
         }
 
         let startup_src = self.program_dir.join("NT.Client.sh");
 
         if startup_src.exists() {
-            if self.startup_bin.exists() {
-                let _ = fs::remove_file(&self.startup_bin);
-            }
-
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::symlink;
-                if let Ok(_) = symlink(&startup_src, &self.startup_bin) {
-                    self.log(&format!("🔗 创建启动脚本符号链接: {}", self.startup_bin.display()));
-                }
+            if self.replace_symlink(&startup_src, &self.startup_bin).is_ok() {
+                self.log(&format!("🔗 创建启动脚本符号链接: {}", self.startup_bin.display()));
             }
 
             let _ = StdCommand::new("chmod")
@@ -1020,6 +2169,7 @@ impl HoloMotionInstaller {
     fn upgrade(&self, channel: &Channel, provided_git_url: Option<&str>) -> Result<()> {
         self.log("⬆️ 开始升级");
         self.assert_repos_exist()?;
+        self.require_network("升级")?;
 
         self.ensure_correct_remote(provided_git_url)?;
         self.fetch_remote()?;
@@ -1035,96 +2185,78 @@ impl HoloMotionInstaller {
             return Ok(());
         }
 
-        self.log("🔄 正在应用更新");
-        self.clean_git_state()?;
-        self.fix_permissions()?;
-
-        self.log(&format!("🔄 正在切换到版本: {}", latest_version));
-        // **修复生命周期问题：预先创建字符串变量**
-        let fetch_refspec = format!("refs/tags/{}:refs/tags/{}", latest_version, latest_version);
-        let tag_ref = format!("tags/{}", latest_version);
-
-        let mut success = false;
+        self.target_version.replace(Some(latest_version.clone()));
+        self.assert_tag_trusted(channel, &latest_version)?;
+        self.migrate_legacy_layout(&current_version)?;
+        let current_channel = fs::read_to_string(&self.branch_file)
+            .map(|content| content.trim().to_string())
+            .unwrap_or_else(|_| channel.as_str().to_string());
 
-        // **方法1: 直接checkout**
-        self.log("🔄 尝试方法1: checkout");
-        let output = StdCommand::new("git")
-            .args(&["checkout", &latest_version])
-            .current_dir(&self.program_dir)
-            .output()?;
+        self.log("🔄 正在应用更新");
+        let version_dir = self.prepare_version_dir(channel, &latest_version)?;
+        let hook_context = HookContext {
+            old_version: Some(&current_version),
+            new_version: Some(&latest_version),
+            channel: channel.as_str(),
+        };
+        self.run_hook("pre-upgrade", &version_dir, &hook_context)?;
 
-        if output.status.success() {
-            success = true;
-            self.log("✅ 使用checkout方式切换版本成功");
-        } else {
-            // **方法2: fetch特定tag然后reset**
-            self.log("🔄 尝试方法2: fetch+reset");
-            let output = StdCommand::new("git")
-                .args(&["fetch", "origin", &fetch_refspec])
-                .current_dir(&self.program_dir)
-                .output()?;
-            if output.status.success() {
-                let reset_output = StdCommand::new("git")
-                    .args(&["reset", "--hard", &latest_version])
-                    .current_dir(&self.program_dir)
-                    .output()?;
+        self.switch_to_version_dir(&version_dir)?;
+        self.create_symlinks()?;
+        self.record_installed_version(Some((&current_version, &current_channel)), &latest_version, channel);
 
-                if reset_output.status.success() {
-                    success = true;
-                    self.log("✅ 使用fetch+reset方式切换版本成功");
-                }
-            }
+        if let Err(e) = self.run_hook("post-upgrade", &self.program_dir, &hook_context) {
+            self.log(&format!("⚠ {}", e));
         }
+        self.prune_versions();
 
-        if !success {
-            // **方法3: fetch all然后reset**
-            self.log("🔄 尝试方法3: fetch-all+reset");
-            let output = StdCommand::new("git")
-                .args(&["fetch", "--all"])
-                .current_dir(&self.program_dir)
-                .output()?;
-
-            if output.status.success() {
-                let reset_output = StdCommand::new("git")
-                    .args(&["reset", "--hard", &latest_version])
-                    .current_dir(&self.program_dir)
-                    .output();
-
-                if reset_output.is_ok() && reset_output.unwrap().status.success() {
-                    success = true;
-                    self.log("✅ 使用fetch-all+reset方式切换版本成功");
-                }
-            }
-        }
+        self.log(&format!("🎉 升级完成! 版本: {} -> {}", current_version, latest_version));
+        Ok(())
+    }
 
-        if !success {
-            // **方法4: 最后尝试使用tags路径**
-            self.log("🔄 尝试方法4: tags路径");
-            let output = StdCommand::new("git")
-                .args(&["reset", "--hard", &tag_ref])
-                .current_dir(&self.program_dir)
-                .output();
+    /// 回滚到版本记录中的上一个版本，可重复执行以继续回退
+    fn rollback(&self) -> Result<()> {
+        self.log("⏪ 开始回滚");
+        self.assert_repos_exist()?;
 
-            if output.is_ok() && output.unwrap().status.success() {
-                success = true;
-                self.log("✅ 使用tags方式切换版本成功");
-            }
+        let mut stack = self.load_version_stack();
+        if stack.installed.len() < 2 {
+            return Err(anyhow!("❌ 没有可回滚的历史版本"));
         }
+        let current = stack.installed.pop().unwrap();
+        let previous = stack.installed.last().unwrap().clone();
+        let channel = Channel::from_str(&previous.channel)?;
+        self.log(&format!("📊 当前版本: {} ({})", current.version, current.channel));
+        self.log(&format!("📊 回滚目标: {} ({})", previous.version, previous.channel));
+        self.target_version.replace(Some(previous.version.clone()));
 
-        if !success {
-            return Err(anyhow!("❌ 所有版本切换方式都失败了"));
-        }
+        self.assert_tag_trusted(&channel, &previous.version)?;
+        self.migrate_legacy_layout(&current.version)?;
 
+        let version_dir = self.prepare_version_dir(&channel, &previous.version)?;
+        self.switch_to_version_dir(&version_dir)?;
         self.create_symlinks()?;
-        self.fix_permissions()?;
-        fs::write(&self.branch_file, channel.as_str())?;
+        self.save_version_stack(&stack)?;
+        self.prune_versions();
 
-        self.log(&format!("🎉 升级完成! 版本: {} -> {}", current_version, latest_version));
+        self.log(&format!("🎉 回滚完成! 版本: {} -> {}", current.version, previous.version));
         Ok(())
     }
 
     fn uninstall(&self) -> Result<()> {
         self.log("🗑️ 开始卸载");
+
+        if self.repos_exist() {
+            let current_version = self.describe_version();
+            let current_channel = fs::read_to_string(&self.branch_file).unwrap_or_default();
+            let hook_context = HookContext {
+                old_version: current_version.as_deref(),
+                new_version: None,
+                channel: current_channel.trim(),
+            };
+            self.run_hook("pre-uninstall", &self.program_dir, &hook_context)?;
+        }
+
         self.clean_installed()?;
         self.log("🎉 卸载完成!");
         Ok(())
@@ -1262,15 +2394,24 @@ StartupNotify=true\n",
             }
         }
 
-        let output = StdCommand::new("git")
-            .args(&["ls-remote", "--tags", "origin"])
-            .current_dir(&self.program_dir)
-            .output()?;
-        if output.status.success() {
-            let tags = String::from_utf8(output.stdout)?;
-            self.log("🌐 远程标签:");
-            for line in tags.lines().take(20) {
-                self.log(&format!("  📡 {}", line));
+        if self.offline.get() {
+            if let Some(cache) = self.load_tag_cache() {
+                self.log(&format!("💾 缓存的远程标签 ({} 秒前):", cache.age_secs()));
+                for tag in cache.tags.iter().rev().take(20) {
+                    self.log(&format!("  📡 {}\trefs/tags/{}", tag.commit, tag.name));
+                }
+            }
+        } else {
+            let output = StdCommand::new("git")
+                .args(["ls-remote", "--tags", "origin"])
+                .current_dir(&self.program_dir)
+                .output()?;
+            if output.status.success() {
+                let tags = String::from_utf8(output.stdout)?;
+                self.log("🌐 远程标签:");
+                for line in tags.lines().take(20) {
+                    self.log(&format!("  📡 {}", line));
+                }
             }
         }
 
@@ -1284,6 +2425,7 @@ StartupNotify=true\n",
         self.log(&format!("📁 程序目录: {:?}", self.program_dir));
         self.log(&format!("🔗 启动脚本: {:?} (存在: {})", self.startup_bin, self.startup_bin.exists()));
         self.log(&format!("⚙️ 安装器脚本: {:?} (存在: {})", self.installer_bin, self.installer_bin.exists()));
+        self.log(&format!("🛠️ 安装器配置: {:?} (存在: {})", self.settings_file, self.settings_file.exists()));
 
         if let Ok(git_url) = self.get_git_url(None) {
             self.log(&format!("✅ Git仓库配置: {}", git_url));
@@ -1293,6 +2435,13 @@ StartupNotify=true\n",
 
         if self.repos_exist() {
             self.log("✅ 应用程序已安装");
+            if let Ok(version_dir) = fs::read_link(&self.program_dir) {
+                self.log(&format!("📂 当前版本目录: {}", version_dir.display()));
+            }
+            let stack = self.load_version_stack();
+            if let Some(previous) = stack.installed.iter().rev().nth(1) {
+                self.log(&format!("⏪ 可回滚到: {} ({})", previous.version, previous.channel));
+            }
 
             if let Ok(channel) = self.get_current_channel(None) {
                 self.log(&format!("📍 当前通道: {}", channel.as_str()));
@@ -1301,9 +2450,16 @@ StartupNotify=true\n",
                     self.log(&format!("🔢 当前版本: {}", current_version));
 
                     let _ = self.ensure_correct_remote(None);
-                    let _ = self.fetch_remote();
 
-                    if let Ok(latest_version) = self.get_latest_version(&channel, None) {
+                    if let Some(policy) = self.settings.trust_policy(&channel) {
+                        self.log(&format!("🔐 标签信任策略: {:?} ({})", policy.format, policy.keyring.display()));
+                    }
+
+                    if let Ok(resolution) = self.resolve_latest_version(&channel, None) {
+                        let latest_version = resolution.version;
+                        for (tag, reason) in &resolution.skipped {
+                            self.log(&format!("⛔ 已跳过标签 {}: {}", tag, reason));
+                        }
                         self.log(&format!("🎯 最新版本: {}", latest_version));
 
                         if current_version == latest_version {
@@ -1328,6 +2484,107 @@ StartupNotify=true\n",
         Ok(())
     }
 
+    /// 不输出日志地读取当前工作目录的版本号
+    fn describe_version(&self) -> Option<String> {
+        if !self.repos_exist() {
+            return None;
+        }
+        let output = StdCommand::new("git")
+            .args(["describe", "--tags"])
+            .current_dir(&self.program_dir)
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let raw_version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        self.extract_version_from_git_describe(&raw_version).ok()
+    }
+
+    fn head_commit(&self) -> Option<String> {
+        if !self.repos_exist() {
+            return None;
+        }
+        let output = StdCommand::new("git")
+            .args(["rev-parse", "HEAD"])
+            .current_dir(&self.program_dir)
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    fn configured_git_url(&self) -> Option<String> {
+        fs::read_to_string(&self.git_file)
+            .map(|content| content.trim().to_string())
+            .ok()
+            .filter(|url| !url.is_empty())
+            .or_else(|| self.get_current_remote_url().ok())
+    }
+
+    /// 需要写入历史日志的操作名称
+    fn journal_action(&self, action: &Action, channel: &Channel) -> Option<&'static str> {
+        match action {
+            Action::Install => Some("install"),
+            Action::Upgrade => {
+                let current_channel = fs::read_to_string(&self.branch_file).unwrap_or_default();
+                if !current_channel.trim().is_empty() && current_channel.trim() != channel.as_str() {
+                    Some("switch-channel")
+                } else {
+                    Some("upgrade")
+                }
+            }
+            Action::Rollback => Some("rollback"),
+            Action::Uninstall => Some("uninstall"),
+            _ => None,
+        }
+    }
+
+    fn append_history(&self, entry: &HistoryEntry) -> Result<()> {
+        fs::create_dir_all(&self.state_dir)?;
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.history_file)?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+
+    fn show_history(&self, json: bool) -> Result<()> {
+        let content = fs::read_to_string(&self.history_file).unwrap_or_default();
+        let entries: Vec<HistoryEntry> = content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+            return Ok(());
+        }
+
+        if entries.is_empty() {
+            println!("暂无安装历史记录");
+            return Ok(());
+        }
+        for entry in &entries {
+            let result = if entry.result == "success" { "成功" } else { "失败" };
+            println!(
+                "{}  {:<14} {} -> {}  [{}]  {}  {:.1}s{}",
+                entry.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                entry.action,
+                entry.from_version.as_deref().unwrap_or("-"),
+                entry.to_version.as_deref().unwrap_or("-"),
+                entry.channel.as_deref().unwrap_or("-"),
+                result,
+                entry.duration_ms as f64 / 1000.0,
+                entry.error.as_deref().map(|e| format!("  错误: {}", e)).unwrap_or_default(),
+            );
+        }
+        Ok(())
+    }
+
     fn execute_action(&self, config: &Config) -> Result<()> {
         self.log(&format!("🎯 执行操作: {:?}, 应用: {}", config.action, config.app_name));
 
@@ -1335,31 +2592,82 @@ StartupNotify=true\n",
             self.kill_process(pid)?;
         }
 
+        if !self.offline.get() && self.auto_offline && !self.remote_reachable() {
+            self.log("📴 远程仓库不可达，自动切换到离线模式");
+            self.offline.set(true);
+        }
+        if self.offline.get() {
+            self.log("📴 离线模式: 仅使用本地状态");
+        }
+
         let channel = config.channel.clone().unwrap_or_else(|| {
             self.get_current_channel(config.git_url.as_deref()).unwrap_or(Channel::Release)
         });
 
+        let Some(journal_action) = self.journal_action(&config.action, &channel) else {
+            return self.run_action(config, &channel);
+        };
+
+        let started = Instant::now();
+        let from_version = self.describe_version();
+        let git_url = config.git_url.clone().or_else(|| self.configured_git_url());
+        let result = self.run_action(config, &channel);
+
+        let entry = match &result {
+            Ok(_) => HistoryEntry {
+                timestamp: Local::now(),
+                action: journal_action.to_string(),
+                from_version,
+                to_version: self.describe_version(),
+                commit: self.head_commit(),
+                channel: fs::read_to_string(&self.branch_file).ok().map(|c| c.trim().to_string()),
+                git_url,
+                duration_ms: started.elapsed().as_millis() as u64,
+                result: "success".to_string(),
+                error: None,
+            },
+            Err(e) => HistoryEntry {
+                timestamp: Local::now(),
+                action: journal_action.to_string(),
+                from_version,
+                to_version: self.target_version.borrow().clone(),
+                commit: None,
+                channel: Some(channel.as_str().to_string()),
+                git_url,
+                duration_ms: started.elapsed().as_millis() as u64,
+                result: "failure".to_string(),
+                error: Some(e.to_string()),
+            },
+        };
+        if let Err(e) = self.append_history(&entry) {
+            self.log(&format!("⚠ 无法写入安装历史: {}", e));
+        }
+
+        result
+    }
+
+    fn run_action(&self, config: &Config, channel: &Channel) -> Result<()> {
         match &config.action {
             Action::GetCurrentChannel => {
                 let current_channel = self.get_current_channel(config.git_url.as_deref())?;
                 println!("{}", current_channel.as_str());
             }
             Action::GetCurrentVersion => {
-                let version = self.get_current_version(&channel, config.git_url.as_deref())?;
+                let version = self.get_current_version(channel, config.git_url.as_deref())?;
                 println!("{}", version);
             }
             Action::GetLatestVersion => {
-                let version = self.get_latest_version(&channel, config.git_url.as_deref())?;
+                let version = self.get_latest_version(channel, config.git_url.as_deref())?;
                 println!("{}", version);
             }
             Action::Install => {
-                self.install(&channel, config.git_url.as_deref())?;
+                self.install(channel, config.git_url.as_deref())?;
                 if config.launch_after {
                     self.launch()?;
                 }
             }
             Action::Upgrade => {
-                self.upgrade(&channel, config.git_url.as_deref())?;if config.launch_after {
+                self.upgrade(channel, config.git_url.as_deref())?;if config.launch_after {
                     self.launch()?;
                 }
             }
@@ -1384,6 +2692,15 @@ StartupNotify=true\n",
             Action::ForceRefresh => {
                 self.force_refresh_tags()?;
             }
+            Action::History => {
+                self.show_history(config.json)?;
+            }
+            Action::Rollback => {
+                self.rollback()?;
+                if config.launch_after {
+                    self.launch()?;
+                }
+            }
             Action::UpdateGitUrl => {
                 if let Some(git_url) = &config.git_url {
                     self.update_git_url(git_url)?;
@@ -1513,10 +2830,55 @@ fn build_cli() -> Command {
             .long("status")
             .help("检查系统和安装状态")
             .action(ArgAction::SetTrue))
+        .arg(Arg::new("rollback")
+            .long("rollback")
+            .help("回滚到上一个安装的版本(可重复执行)")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("history")
+            .long("history")
+            .help("查看安装/升级/回滚历史记录")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("json")
+            .long("json")
+            .help("以JSON格式输出(用于 --history)")
+            .action(ArgAction::SetTrue))
         .arg(Arg::new("force-refresh")
             .long("force-refresh")
             .help("强制刷新远程标签")
             .action(ArgAction::SetTrue))
+        .arg(Arg::new("cache-ttl")
+            .long("cache-ttl")
+            .value_name("SECS")
+            .help("远程标签缓存有效期(秒)，0表示每次都重新获取")
+            .value_parser(clap::value_parser!(u64))
+            .num_args(1))
+        .arg(Arg::new("offline")
+            .long("offline")
+            .help("离线模式: 不访问网络，仅使用本地标签和缓存")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("auto-offline")
+            .long("auto-offline")
+            .help("远程仓库不可达时自动进入离线模式")
+            .conflicts_with("offline")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("keep-versions")
+            .long("keep-versions")
+            .value_name("COUNT")
+            .help("升级后保留的版本目录数量(含当前版本，默认3)")
+            .value_parser(clap::value_parser!(usize))
+            .num_args(1))
+        .arg(Arg::new("preserve")
+            .long("preserve")
+            .value_name("GLOB")
+            .help("升级时保留的本地文件(可多次指定)，如 config/*.ini")
+            .action(ArgAction::Append)
+            .num_args(1))
+        .arg(Arg::new("hook-timeout")
+            .long("hook-timeout")
+            .value_name("SECS")
+            .help("发布包钩子脚本的超时时间(秒，默认300)")
+            .value_parser(clap::value_parser!(u64))
+            .num_args(1))
         .arg(Arg::new("update-git-url")
             .long("update-git-url")
             .value_name("GIT_URL")
@@ -1532,7 +2894,7 @@ fn build_cli() -> Command {
                 "install", "upgrade", "uninstall", "launch-only",
                 "create-desktop", "remove-desktop",
                 "debug-tags", "status", "force-refresh",
-                "update-git-url", "version", "help"]))
+                "update-git-url", "rollback", "history", "version", "help"]))
 }
 
 fn main() -> Result<()> {
@@ -1549,7 +2911,7 @@ fn main() -> Result<()> {
     }
 
     let config = Config::from_matches(&matches)?;
-    let installer = HoloMotionInstaller::new(Some(&config.app_name))?;
+    let installer = HoloMotionInstaller::new(&config)?;
     installer.execute_action(&config)?;
 
     Ok(())